@@ -1,3 +1,6 @@
+use crate::span::Span;
+
+use console::Style;
 use failure::Fail;
 use std::fmt;
 
@@ -16,28 +19,76 @@ pub enum CompilationErrorKind {
     Internal {
         message: String,
     },
+    #[fail(display = "unindent does not match any outer indentation level")]
+    UnindentDoesNotMatch,
+    #[fail(display = "unterminated interpolation")]
+    UnterminatedInterpolation,
 }
 
+/// A compile error together with everything `Display` needs to render a
+/// codespan-style source snippet for it, without requiring callers to carry
+/// the original source text alongside the error.
 #[derive(Debug, Fail)]
-#[fail(display = "{} at {}:{}:{}", kind, filename, line, column)]
 pub struct CompilationError {
+    pub span: Span,
     pub line: usize,
     pub column: usize,
+    /// Column just past the offending span, on the same line as `column`
+    pub end_column: usize,
+    pub line_text: String,
     pub filename: String,
     pub kind: CompilationErrorKind,
 }
 
 impl CompilationError {
-    pub fn new(kind: CompilationErrorKind, filename: String, line: usize, column: usize) -> Self {
+    /// Builds a `CompilationError`, resolving `span` against `source` up
+    /// front so the error can render its snippet on its own later.
+    pub fn new(kind: CompilationErrorKind, filename: String, span: Span, source: &str) -> Self {
+        let (line, column, end_column) = span.line_col(source);
+        let line_text = source.lines().nth(line - 1).unwrap_or("").to_owned();
         Self {
+            span,
             line,
             column,
+            end_column,
+            line_text,
             filename,
             kind,
         }
     }
 }
 
+impl fmt::Display for CompilationError {
+    /// Renders a codespan-style snippet of this error: the offending line,
+    /// underlined beneath the exact column range it occurred at, colored
+    /// via `console` (honoring the user's `--color` setting).
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gutter = format!("{} | ", self.line);
+
+        let underline_start = self.column.saturating_sub(1);
+        let underline_len = self.end_column.saturating_sub(self.column).max(1);
+        let pointer = "^".repeat(underline_len);
+
+        let error_style = Style::new().red().bold();
+        let location_style = Style::new().blue();
+
+        write!(
+            f,
+            "{}: {}\n  {} {}:{}:{}\n{}{}\n{}{}",
+            error_style.apply_to("error"),
+            self.kind,
+            "-->",
+            location_style.apply_to(&self.filename),
+            self.line,
+            self.column,
+            gutter,
+            self.line_text,
+            " ".repeat(gutter.len() + underline_start),
+            error_style.apply_to(pointer),
+        )
+    }
+}
+
 #[derive(Debug, Fail)]
 pub struct MultipleErrors {
     pub errs: Vec<failure::Error>,
@@ -49,17 +100,42 @@ impl fmt::Display for MultipleErrors {
             writeln!(f, "multiple errors:")?;
         }
         if self.errs.len() > 5 {
-            for err_idx in 0..5 {
-                writeln!(f, "\t{}", self.errs[err_idx])?;
+            for err in &self.errs[..5] {
+                writeln!(f, "{}", err)?;
             }
-            writeln!(f, "\t{} other errors omitted.", self.errs.len() - 5)?;
+            writeln!(f, "{} other errors omitted.", self.errs.len() - 5)?;
         } else {
             for err in self.errs.iter() {
-                writeln!(f, "\t{}", err)?;
+                writeln!(f, "{}", err)?;
             }
         }
         Ok(())
     }
 }
 
-pub type Result<T> = std::result::Result<T, failure::Error>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, failure::Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::{CompilationError, CompilationErrorKind};
+    use crate::span::Span;
+
+    #[test]
+    fn test_display_renders_snippet_with_location_and_line_text() {
+        let source = "foo:\n    echo @bar\n";
+        let at = source.find('@').unwrap();
+        let err = CompilationError::new(
+            CompilationErrorKind::UnexpectedChar { ch: '@' },
+            "pleasefile".to_owned(),
+            Span::new(at, at + 1),
+            source,
+        );
+
+        let rendered = err.to_string();
+
+        assert!(rendered.contains("unexpected character '@'"));
+        assert!(rendered.contains("pleasefile:2:10"));
+        assert!(rendered.contains("    echo @bar"));
+        assert!(rendered.contains('^'));
+    }
+}
\ No newline at end of file
@@ -1,4 +1,5 @@
 use crate::error;
+use crate::span::Span;
 
 use internship::IStr;
 use serde::{Serialize, Deserialize};
@@ -56,6 +57,8 @@ pub fn interned_keywords() -> Vec<IStr> {
 pub struct Token<'a> {
     pub line: usize,
     pub col: usize,
+    /// The token's byte range in the source it was lexed from
+    pub span: Span,
     pub kind: TokenKind,
     pub filename: &'a str,
 }
@@ -67,13 +70,13 @@ impl<'a> fmt::Display for Token<'a> {
 }
 
 impl<'a> Token<'a> {
-    pub fn error(&self, kind: error::CompilationErrorKind) -> error::CompilationError {
-        error::CompilationError {
-            column: self.col,
-            line: self.line,
-            filename: String::from(self.filename),
-            kind,
-        }
+    /// This token's byte range in the source it was lexed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn error(&self, kind: error::CompilationErrorKind, source: &str) -> error::CompilationError {
+        error::CompilationError::new(kind, String::from(self.filename), self.span, source)
     }
 }
 
@@ -82,12 +85,15 @@ pub enum State {
     Normal,
     Indented { indentation: usize },
     Text,
-    Interpolation { interpolation_start_col: usize, interpolation_start_row: usize }
+    Interpolation { interpolation_start_offset: usize }
 }
 
 pub struct Lexer<'a> {
     /// Source filename
     filename: &'a str,
+    /// The full source text being lexed, kept around so diagnostics can
+    /// quote the offending line
+    source: &'a str,
     /// Peekable char iterator
     input: Peekable<Chars<'a>>,
     /// Current token
@@ -98,10 +104,14 @@ pub struct Lexer<'a> {
     line: usize,
     /// Current column
     col: usize,
+    /// Current byte offset into `input`'s source text
+    offset: usize,
     /// Accrued errors
     errs: Vec<failure::Error>,
     /// State stack
     state: Vec<State>,
+    /// Stack of indentation column widths currently open, outermost first
+    indent_stack: Vec<usize>,
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -150,51 +160,218 @@ impl<'a> Lexer<'a> {
     fn new(text: &'a str, filename: &'a str) -> error::Result<Self> {
         let mut res = Self {
             filename,
+            source: text,
             input: text.chars().peekable(),
             token: None,
             ch: Some('\0'),
             line: 0,
             col: 0,
+            offset: 0,
             errs: Vec::new(),
             state: vec![State::Normal],
+            indent_stack: vec![0],
         };
         res.bump()?;
         Ok(res)
     }
 
     fn advance(&mut self) -> error::Result<()> {
-        if self.is_eof() {
-            self.token = None;
-            return Ok(())
-        }
+        loop {
+            let state = self.state()?;
+            let in_interpolation = match state {
+                State::Interpolation { .. } => true,
+                _ => false,
+            };
+
+            if self.is_eof() && !in_interpolation {
+                // Unwind any indentation levels still open before calling it quits.
+                if self.indent_stack.len() > 1 {
+                    self.indent_stack.pop();
+                    self.token = Some(self.make_token(TokenKind::Dedent));
+                    return Ok(());
+                }
+                self.token = None;
+                return Ok(());
+            }
 
-        match self.state()? {
-            State::Normal => self.lex_normal()?,
-            _ => return Err(self.internal_error("Unexpected state")),
-        }
+            let produced = match state {
+                State::Normal => self.lex_normal()?,
+                State::Indented { indentation } => self.lex_indented(indentation)?,
+                State::Text => self.lex_text()?,
+                State::Interpolation { interpolation_start_offset } => {
+                    self.lex_interpolation(interpolation_start_offset)?
+                }
+            };
 
-        Ok(())
+            if produced {
+                return Ok(());
+            }
+        }
     }
 
-    fn lex_normal(&mut self) -> error::Result<()> {
-        if self.ch_is('@') {
+    fn lex_normal(&mut self) -> error::Result<bool> {
+        if self.ch_is(' ') || self.ch_is('\t') {
+            self.bump()?;
+            Ok(false)
+        } else if self.ch_is('\n') {
+            self.bump()?;
+            let indentation = self.measure_indentation()?;
+            self.state.push(State::Indented { indentation });
+            Ok(false)
+        } else if self.ch_is('@') {
             self.lex_single(TokenKind::At)?;
+            Ok(true)
         } else if self.ch_is(':') {
             self.lex_single(TokenKind::Colon)?;
+            Ok(true)
+        } else if self.ch_is('=') {
+            self.lex_single(TokenKind::Assign)?;
+            Ok(true)
+        } else if self.ch_is('+') {
+            if self.nextch_is('=') {
+                self.lex_double(TokenKind::AddAssign)?;
+            } else {
+                self.lex_single(TokenKind::Add)?;
+            }
+            Ok(true)
         } else if self.ch_is('(') {
             self.lex_single(TokenKind::ParenL)?;
+            Ok(true)
         } else if self.ch_is(')') {
             self.lex_single(TokenKind::ParenR)?;
+            Ok(true)
         } else if is_ident_start(self.ch) {
             self.lex_identifier()?;
+            Ok(true)
         } else {
             let err = self.error(error::CompilationErrorKind::UnexpectedChar {
                 ch: self.ch.unwrap()
             });
             self.bump()?;
-            return Err(err);
+            Err(err)
+        }
+    }
+
+    /// Consumes leading spaces on the line the cursor currently sits at the
+    /// start of, skipping over any entirely-blank lines, and returns the
+    /// width of the first non-blank line found (or 0 at EOF).
+    fn measure_indentation(&mut self) -> error::Result<usize> {
+        loop {
+            if self.is_eof() {
+                return Ok(0);
+            }
+            let mut width = 0;
+            while self.ch_is(' ') {
+                width += 1;
+                self.bump()?;
+            }
+            if self.ch_is('\n') {
+                self.bump()?;
+                continue;
+            }
+            if self.is_eof() {
+                return Ok(0);
+            }
+            return Ok(width);
+        }
+    }
+
+    /// Compares `indentation`, the already-measured width of the line the
+    /// cursor is now sitting at the start of, against the indentation stack,
+    /// emitting one `Indent`/`Dedent` token per call until they match.
+    fn lex_indented(&mut self, indentation: usize) -> error::Result<bool> {
+        let top = *self.indent_stack.last().expect("indent stack is never empty");
+
+        if indentation > top {
+            self.indent_stack.push(indentation);
+            let token = self.make_token(TokenKind::Indent);
+            self.pop_state()?;
+            self.state.push(State::Text);
+            self.token = Some(token);
+            Ok(true)
+        } else if indentation < top {
+            self.indent_stack.pop();
+            let new_top = *self.indent_stack.last().expect("indent stack is never empty");
+            if indentation > new_top {
+                return Err(self.error(error::CompilationErrorKind::UnindentDoesNotMatch));
+            }
+            self.token = Some(self.make_token(TokenKind::Dedent));
+            Ok(true)
+        } else {
+            self.pop_state()?;
+            self.state.push(self.next_body_state());
+            Ok(false)
+        }
+    }
+
+    /// The state to enter once indentation has settled at the current
+    /// `indent_stack` depth: `Normal` at the top level (column 0, where
+    /// recipe headers live), `Text` anywhere nested inside a recipe body.
+    fn next_body_state(&self) -> State {
+        if self.indent_stack.len() == 1 {
+            State::Normal
+        } else {
+            State::Text
         }
-        Ok(())
+    }
+
+    /// Accumulates raw characters into a `Command` token until end-of-line or
+    /// the start of an interpolation (`{{`).
+    fn lex_text(&mut self) -> error::Result<bool> {
+        if self.is_eof() {
+            self.pop_state()?;
+            return Ok(false);
+        }
+        if self.ch_is('\n') {
+            self.bump()?;
+            self.pop_state()?;
+            let indentation = self.measure_indentation()?;
+            self.state.push(State::Indented { indentation });
+            return Ok(false);
+        }
+        if self.ch_is('{') && self.nextch_is('{') {
+            let interpolation_start_offset = self.offset;
+            self.lex_double(TokenKind::InterpolationStart)?;
+            self.state.push(State::Interpolation {
+                interpolation_start_offset,
+            });
+            return Ok(true);
+        }
+
+        let (line, col, start_offset) = (self.line, self.col, self.offset);
+        let mut command = String::new();
+        while !self.is_eof() && !self.ch_is('\n') && !(self.ch_is('{') && self.nextch_is('{')) {
+            command.push(self.ch.unwrap());
+            self.bump()?;
+        }
+        self.token = Some(Token {
+            line,
+            col,
+            span: Span::new(start_offset, self.offset),
+            kind: TokenKind::Command(IStr::from(command)),
+            filename: self.filename,
+        });
+        Ok(true)
+    }
+
+    /// Lexes normal tokens inside an interpolation until the closing `}}`.
+    fn lex_interpolation(&mut self, interpolation_start_offset: usize) -> error::Result<bool> {
+        if self.is_eof() {
+            // Pop the Interpolation state before erroring, so the next call to
+            // advance() sees a state that actually terminates at EOF instead
+            // of landing right back here and looping forever.
+            self.pop_state()?;
+            return Err(self.error_at(
+                error::CompilationErrorKind::UnterminatedInterpolation,
+                Span::new(interpolation_start_offset, interpolation_start_offset + 2),
+            ));
+        }
+        if self.ch_is('}') && self.nextch_is('}') {
+            self.pop_state()?;
+            self.lex_double(TokenKind::InterpolationEnd)?;
+            return Ok(true);
+        }
+        self.lex_normal()
     }
 
     fn state(&self) -> error::Result<State> {
@@ -215,24 +392,24 @@ impl<'a> Lexer<'a> {
 
     /// Lex a single character token
     fn lex_single(&mut self, kind: TokenKind) -> error::Result<()> {
-        let token = self.make_token(kind);
+        let (line, col, start_offset) = (self.line, self.col, self.offset);
         self.bump()?;
-        self.token = Some(token);
+        self.token = Some(self.make_token_at(kind, line, col, start_offset, self.offset));
         Ok(())
     }
 
     /// Lex a double character token
     fn lex_double(&mut self, kind: TokenKind) -> error::Result<()> {
-        let token = self.make_token(kind);
+        let (line, col, start_offset) = (self.line, self.col, self.offset);
         self.bump()?;
         self.bump()?;
-        self.token = Some(token);
+        self.token = Some(self.make_token_at(kind, line, col, start_offset, self.offset));
         Ok(())
     }
 
     /// Lex identifier: [a-zA-Z_][a-zA-Z0-9_]*
     fn lex_identifier(&mut self) -> error::Result<()> {
-        let (line, col) = (self.line, self.col);
+        let (line, col, start_offset) = (self.line, self.col, self.offset);
         let mut ident = String::new();
         while is_ident_continue(self.ch) {
             ident.push(self.ch.unwrap());
@@ -247,16 +424,23 @@ impl<'a> Lexer<'a> {
         self.token = Some(Token {
             line,
             col,
+            span: Span::new(start_offset, self.offset),
             kind,
             filename: self.filename
         });
         Ok(())
     }
 
+    /// Makes a zero-width token at the lexer's current position (e.g. `Indent`/`Dedent`)
     fn make_token(&self, kind: TokenKind) -> Token<'a> {
+        self.make_token_at(kind, self.line, self.col, self.offset, self.offset)
+    }
+
+    fn make_token_at(&self, kind: TokenKind, line: usize, col: usize, start_offset: usize, end_offset: usize) -> Token<'a> {
         Token {
-            line: self.line,
-            col: self.col,
+            line,
+            col,
+            span: Span::new(start_offset, end_offset),
             kind,
             filename: self.filename,
         }
@@ -269,10 +453,12 @@ impl<'a> Lexer<'a> {
                 '\n' => {
                     self.col = 0;
                     self.line += 1;
+                    self.offset += c.len_utf8();
                 },
                 '\0' => {},
                 _ => {
                     self.col += c.len_utf8();
+                    self.offset += c.len_utf8();
                 }
             }
             self.ch = self.input.next();
@@ -319,7 +505,15 @@ impl<'a> Lexer<'a> {
 
     #[inline]
     fn error(&self, kind: error::CompilationErrorKind) -> failure::Error {
-        failure::Error::from(error::CompilationError::new(kind, self.filename.to_owned(), self.line + 1, self.col + 1)) // Add 1 to line and col to offset 0-indexing
+        self.error_at(kind, Span::new(self.offset, self.offset + 1))
+    }
+
+    /// Builds an error pointing at an explicit `span` rather than the
+    /// lexer's current position, e.g. the opening delimiter of a construct
+    /// that turned out to be unterminated.
+    #[inline]
+    fn error_at(&self, kind: error::CompilationErrorKind, span: Span) -> failure::Error {
+        failure::Error::from(error::CompilationError::new(kind, self.filename.to_owned(), span, self.source))
     }
 
     #[inline]
@@ -352,7 +546,9 @@ fn is_ident_continue(c: Option<char>) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use super::{Lexer, TokenKind};
     use insta::assert_yaml_snapshot_matches;
+    use internship::IStr;
 
     #[test]
     fn test_lexer_rustplease() -> std::io::Result<()> {
@@ -362,4 +558,53 @@ mod tests {
         assert_yaml_snapshot_matches!("tokens", tokens);
         Ok(())
     }
+
+    #[test]
+    fn test_lexer_dedent_returns_to_normal_state() {
+        let tokens = Lexer::lex("foo:\n    echo hi\n\nbar:\n    echo there\n", "test").unwrap();
+        let kinds: Vec<&TokenKind> = tokens.iter().map(|t| &t.kind).collect();
+
+        assert_eq!(kinds[0], &TokenKind::Identifier(IStr::from("foo")));
+        assert_eq!(kinds[1], &TokenKind::Colon);
+
+        let second_identifier = kinds
+            .iter()
+            .skip(2)
+            .position(|kind| **kind == TokenKind::Identifier(IStr::from("bar")))
+            .expect("second recipe header should lex as an Identifier, not a Command");
+        assert_eq!(kinds[2 + second_identifier + 1], &TokenKind::Colon);
+    }
+
+    #[test]
+    fn test_lexer_unterminated_interpolation_at_eof_errors() {
+        let result = Lexer::lex("foo:\n    echo {{abc", "test");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_lexer_assign_add_and_add_assign() {
+        let tokens = Lexer::lex("a = one\nb += two\nc + three", "test").unwrap();
+        let kinds: Vec<&TokenKind> = tokens.iter().map(|t| &t.kind).collect();
+
+        assert_eq!(kinds[1], &TokenKind::Assign);
+        assert_eq!(kinds[4], &TokenKind::AddAssign);
+        assert_eq!(kinds[7], &TokenKind::Add);
+    }
+
+    #[test]
+    fn test_lexer_add_assign_is_not_two_single_tokens() {
+        let tokens = Lexer::lex("a += one", "test").unwrap();
+        let kinds: Vec<&TokenKind> = tokens.iter().map(|t| &t.kind).collect();
+
+        assert!(!kinds.contains(&&TokenKind::Assign));
+        assert!(kinds.contains(&&TokenKind::AddAssign));
+    }
+
+    #[test]
+    fn test_lexer_recipe_body_line_is_a_command_token() {
+        let tokens = Lexer::lex("foo:\n    echo hi\n", "test").unwrap();
+        let kinds: Vec<&TokenKind> = tokens.iter().map(|t| &t.kind).collect();
+
+        assert!(kinds.contains(&&TokenKind::Command(IStr::from("echo hi"))));
+    }
 }
\ No newline at end of file
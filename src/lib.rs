@@ -4,7 +4,9 @@
 
 mod error;
 mod lexer;
+mod loader;
 mod parser;
 mod run;
+mod span;
 
 pub use run::run;
\ No newline at end of file
@@ -0,0 +1,69 @@
+//! Owns the source text of every pleasefile that has been loaded.
+
+use crate::error;
+
+use failure::ResultExt;
+use typed_arena::Arena;
+
+use std::path::Path;
+
+/// Owns the source text of every pleasefile loaded during a run, handing out
+/// stable `&str` slices that tokens and diagnostics can borrow from for as
+/// long as the `Loader` itself is alive.
+#[derive(Default)]
+pub struct Loader {
+    arena: Arena<String>,
+}
+
+impl Loader {
+    /// Creates a new, empty loader.
+    pub fn new() -> Self {
+        Self {
+            arena: Arena::new(),
+        }
+    }
+
+    /// Reads the pleasefile at `path` into the arena and returns a reference
+    /// to its contents that lives as long as `self`.
+    pub fn load(&self, path: &Path) -> error::Result<&str> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|_| format!("Unable to read {:?}", path))?;
+        Ok(self.arena.alloc(text))
+    }
+
+    /// Interns an arbitrary string (e.g. a filename) in the loader's arena so
+    /// it can be borrowed for as long as `self`, alongside loaded source text.
+    pub fn intern(&self, s: String) -> &str {
+        self.arena.alloc(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Loader;
+
+    #[test]
+    fn test_load_returns_file_contents() {
+        let path = std::env::temp_dir().join("please_loader_test_please_file");
+        std::fs::write(&path, "build:\n    cargo build\n").unwrap();
+
+        let loader = Loader::new();
+        let text = loader.load(&path).unwrap();
+
+        assert_eq!(text, "build:\n    cargo build\n");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_missing_file_errors() {
+        let loader = Loader::new();
+        assert!(loader.load(std::path::Path::new("/no/such/pleasefile")).is_err());
+    }
+
+    #[test]
+    fn test_intern_returns_matching_str() {
+        let loader = Loader::new();
+        assert_eq!(loader.intern("hello".to_owned()), "hello");
+    }
+}
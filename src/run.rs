@@ -1,3 +1,6 @@
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::loader::Loader;
+
 use failure::ResultExt;
 
 use structopt::clap::Shell;
@@ -44,6 +47,11 @@ struct Opt {
     #[structopt(short, long)]
     list: bool,
 
+    /// Require recipe names given in <ARGUMENTS> to match exactly, rather
+    /// than resolving an unambiguous prefix to the recipe it abbreviates
+    #[structopt(long)]
+    no_abbrev: bool,
+
     /// Suppress all output
     #[structopt(short, long)]
     quiet: bool,
@@ -158,6 +166,7 @@ pub fn run() -> Result<(), failure::Error> {
 
     log::info!("Looking for pleasefile...");
 
+    let loader = Loader::new();
     let mut parsed_files = Vec::new();
     let mut cwd = std::env::current_dir()
         .with_context(|_| "Unable to read current directory")?;
@@ -166,11 +175,12 @@ pub fn run() -> Result<(), failure::Error> {
 
         log::debug!("Looking for {:?}", filename);
 
-        let file = std::fs::read_to_string(filename);
-        if let Ok(file) = file {
-            // TODO: Parse file
+        if filename.is_file() {
+            let text = loader.load(&filename)?;
+            let name = loader.intern(filename.to_string_lossy().into_owned());
+            let tokens = Lexer::lex(text, name)?;
 
-            parsed_files.push(file);
+            parsed_files.push(tokens);
         }
 
         let try_parent = cwd.parent();
@@ -183,5 +193,110 @@ pub fn run() -> Result<(), failure::Error> {
 
     log::info!("Parsed {} files", parsed_files.len());
 
+    let recipes: Vec<String> = parsed_files.iter().flat_map(|tokens| recipe_names(tokens)).collect();
+
+    for pattern in &opt.arguments {
+        match resolve_recipe(pattern, &recipes, !opt.no_abbrev) {
+            Some(Ok(name)) => {
+                // No recipe executor exists yet; recognize the dispatch target.
+                log::info!("Running recipe '{}'", name);
+            }
+            Some(Err(candidates)) => {
+                report_ambiguous_recipe(pattern, &candidates);
+                return Err(failure::format_err!("'{}' is an ambiguous recipe name", pattern));
+            }
+            None => {
+                return Err(failure::format_err!("no recipe named '{}' found", pattern));
+            }
+        }
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Resolves `pattern` against `recipes`: an exact match always wins, and
+/// when `allow_abbrev` is set an unambiguous prefix match resolves the same
+/// way. Returns `None` if nothing matches `pattern`, `Some(Ok(name))` if a
+/// single recipe does, and `Some(Err(candidates))` listing every recipe that
+/// shares the prefix when more than one does.
+fn resolve_recipe<'a>(pattern: &str, recipes: &'a [String], allow_abbrev: bool) -> Option<Result<&'a str, Vec<&'a str>>> {
+    if let Some(exact) = recipes.iter().find(|name| name.as_str() == pattern) {
+        return Some(Ok(exact.as_str()));
+    }
+
+    if !allow_abbrev {
+        return None;
+    }
+
+    let candidates: Vec<&str> = recipes
+        .iter()
+        .map(String::as_str)
+        .filter(|name| name.starts_with(pattern))
+        .collect();
+
+    match candidates.len() {
+        0 => None,
+        1 => Some(Ok(candidates[0])),
+        _ => Some(Err(candidates)),
+    }
+}
+
+/// Collects the names of every recipe declared at the top level of a lexed
+/// pleasefile: an `Identifier` immediately followed by a `Colon`, outside of
+/// any `Indent`/`Dedent` nesting.
+fn recipe_names(tokens: &[Token<'_>]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut depth = 0usize;
+    let mut iter = tokens.iter().peekable();
+    while let Some(token) = iter.next() {
+        match &token.kind {
+            TokenKind::Indent => depth += 1,
+            TokenKind::Dedent => depth -= 1,
+            TokenKind::Identifier(name) if depth == 0 => {
+                if let Some(next) = iter.peek() {
+                    if next.kind == TokenKind::Colon {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Prints an `error:`-styled diagnostic, matching `CompilationError`'s own
+/// `Display` styling, listing the recipes an ambiguous abbreviation could
+/// refer to.
+fn report_ambiguous_recipe(pattern: &str, candidates: &[&str]) {
+    let error_style = console::Style::new().red().bold();
+    eprintln!(
+        "{}: recipe abbreviation '{}' is ambiguous; it could refer to {}",
+        error_style.apply_to("error"),
+        pattern,
+        candidates.join(", "),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{recipe_names, resolve_recipe};
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_recipe_names_collects_every_top_level_recipe() {
+        let tokens = Lexer::lex("build:\n    cargo build\n\ntest:\n    cargo test\n\nrun: build\n    cargo run\n", "test").unwrap();
+        assert_eq!(recipe_names(&tokens), vec!["build", "test", "run"]);
+    }
+
+    #[test]
+    fn test_resolve_recipe_abbreviates_unambiguous_prefix() {
+        let recipes = vec!["build".to_string(), "test".to_string(), "bundle".to_string()];
+
+        assert_eq!(resolve_recipe("test", &recipes, true), Some(Ok("test")));
+        assert_eq!(resolve_recipe("te", &recipes, true), Some(Ok("test")));
+        assert_eq!(resolve_recipe("bu", &recipes, true).unwrap().unwrap_err(), vec!["build", "bundle"]);
+        assert_eq!(resolve_recipe("bu", &recipes, false), None);
+        assert_eq!(resolve_recipe("nope", &recipes, true), None);
+    }
+}
@@ -0,0 +1,75 @@
+//! Byte-offset source ranges, used to locate tokens and errors precisely
+//! within a pleasefile's source text, independent of how it was tokenized.
+
+use serde::{Deserialize, Serialize};
+
+/// A half-open byte range `[start, end)` into a source file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Span {
+    /// Byte offset of the span's first character
+    pub start: usize,
+    /// Byte offset just past the span's last character
+    pub end: usize,
+}
+
+impl Span {
+    /// Creates a new span covering the half-open range `[start, end)`.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The slice of `source` this span covers.
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+
+    /// Translates this span's start into a 1-indexed `(line, column)`, and
+    /// its end into the column just past the span on that same line, by
+    /// scanning `source` for line breaks up to `self.start`.
+    pub fn line_col(&self, source: &str) -> (usize, usize, usize) {
+        let mut line = 1;
+        let mut line_start = 0;
+        for (i, ch) in source.char_indices() {
+            if i >= self.start {
+                break;
+            }
+            if ch == '\n' {
+                line += 1;
+                line_start = i + ch.len_utf8();
+            }
+        }
+        let column = self.start - line_start + 1;
+        let end_column = column + (self.end.saturating_sub(self.start)).max(1);
+        (line, column, end_column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Span;
+
+    #[test]
+    fn test_text_slices_source() {
+        let source = "foo: bar";
+        assert_eq!(Span::new(5, 8).text(source), "bar");
+    }
+
+    #[test]
+    fn test_line_col_on_first_line() {
+        let source = "foo: bar\n";
+        assert_eq!(Span::new(0, 3).line_col(source), (1, 1, 4));
+    }
+
+    #[test]
+    fn test_line_col_on_later_line() {
+        let source = "foo:\n    bar\n";
+        let start = source.find("bar").unwrap();
+        assert_eq!(Span::new(start, start + 3).line_col(source), (2, 5, 8));
+    }
+
+    #[test]
+    fn test_line_col_empty_span_has_width_one() {
+        let source = "foo: bar\n";
+        assert_eq!(Span::new(4, 4).line_col(source), (1, 5, 6));
+    }
+}